@@ -0,0 +1,176 @@
+// Detection of the host's vCPU signature (family/model/stepping) via CPUID
+// leaf 0x1, mapped to the `CpuType` variants the `sev` crate's launch-digest
+// routines expect. Getting this wrong is the most common source of
+// "measurement mismatch because the wrong CPU model was assumed" failures,
+// since the launch digest folds in a VMSA built for a specific vCPU type.
+
+use sev::measurement::vcpu_types::CpuType;
+
+/// Decoded family/model/stepping, as found in CPUID.01H:EAX.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CpuSignature {
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+}
+
+impl CpuSignature {
+    /// Decodes the packed family/model/stepping fields from a raw
+    /// CPUID.01H:EAX value, applying the AMD/Intel extended family/model
+    /// rules (Family >= 0xF folds in ExtFamily; Family 0x6 or 0xF folds in
+    /// ExtModel).
+    pub fn from_eax(eax: u32) -> Self {
+        let base_family = (eax >> 8) & 0xF;
+        let base_model = (eax >> 4) & 0xF;
+        let stepping = eax & 0xF;
+        let ext_family = (eax >> 20) & 0xFF;
+        let ext_model = (eax >> 16) & 0xF;
+
+        let family = if base_family == 0xF {
+            base_family + ext_family
+        } else {
+            base_family
+        };
+        let model = if base_family == 0xF || base_family == 0x6 {
+            (ext_model << 4) | base_model
+        } else {
+            base_model
+        };
+
+        CpuSignature {
+            family,
+            model,
+            stepping,
+        }
+    }
+
+    /// Re-packs family/model/stepping into a raw CPUID.01H:EAX value, the
+    /// inverse of [`Self::from_eax`]. This is what SEV-ES guests expect as
+    /// `vcpu_sig`: unlike SNP, the SEV-ES launch digest folds in the actual
+    /// CPUID signature bits rather than a `CpuType` enum discriminant.
+    pub fn to_eax(self) -> u32 {
+        let needs_ext = self.family >= 0xF;
+        let (base_family, ext_family) = if needs_ext {
+            (0xF, self.family - 0xF)
+        } else {
+            (self.family, 0)
+        };
+        let needs_ext_model = self.family >= 0xF || self.family == 0x6;
+        let (base_model, ext_model) = if needs_ext_model {
+            (self.model & 0xF, (self.model >> 4) & 0xF)
+        } else {
+            (self.model, 0)
+        };
+
+        (ext_family << 20) | (ext_model << 16) | (base_family << 8) | (base_model << 4) | self.stepping
+    }
+}
+
+/// Issues CPUID leaf 0x1 on the running host and decodes its signature.
+#[cfg(target_arch = "x86_64")]
+pub fn detect_cpu_signature() -> Option<CpuSignature> {
+    detect_raw_eax().map(CpuSignature::from_eax)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn detect_cpu_signature() -> Option<CpuSignature> {
+    None
+}
+
+/// Issues CPUID leaf 0x1 on the running host and returns the raw EAX value,
+/// undecoded. Preferred over `detect_cpu_signature` when the caller just
+/// needs to forward the signature (e.g. SEV-ES's `vcpu_sig`), since it
+/// avoids a decode/re-encode round trip.
+#[cfg(target_arch = "x86_64")]
+pub fn detect_raw_eax() -> Option<u32> {
+    // Safety: CPUID leaf 0x1 (basic processor info) is available on every
+    // x86_64 CPU and takes no arguments beyond the leaf number.
+    let result = unsafe { std::arch::x86_64::__cpuid(0x1) };
+    Some(result.eax)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn detect_raw_eax() -> Option<u32> {
+    None
+}
+
+/// Maps a decoded family/model to the closest AMD EPYC `CpuType` variant.
+/// Family 0x19 covers Milan and Genoa; the model ranges below split them,
+/// matching the generations the `sev` crate's `CpuType` enumerates.
+pub fn cpu_type_from_signature(sig: CpuSignature) -> Option<CpuType> {
+    match (sig.family, sig.model) {
+        (0x19, 0x00..=0x0F) => Some(CpuType::EpycMilan),
+        (0x19, 0x10..=0x1F) => Some(CpuType::EpycGenoa),
+        (0x17, 0x30..=0x3F) => Some(CpuType::EpycRome),
+        (0x17, _) => Some(CpuType::Epyc),
+        _ => None,
+    }
+}
+
+/// Inverse of [`cpu_type_from_signature`]: a representative family/model for
+/// each EPYC generation, used to synthesize a CPUID signature when the vCPU
+/// type came from `--vcpu-type`/detected `CpuType` rather than a raw CPUID
+/// readout (e.g. building SEV-ES's `vcpu_sig`). Picks the first model in the
+/// generation's range, stepping 0, since the launch digest only needs *a*
+/// signature consistent with the chosen `CpuType`, not the exact silicon.
+pub fn representative_signature(cpu_type: CpuType) -> CpuSignature {
+    let (family, model) = match cpu_type {
+        CpuType::EpycMilan | CpuType::EpycMilanV1 | CpuType::EpycMilanV2 => (0x19, 0x00),
+        CpuType::EpycGenoa | CpuType::EpycGenoaV1 => (0x19, 0x10),
+        CpuType::EpycRome | CpuType::EpycRomeV1 | CpuType::EpycRomeV2 | CpuType::EpycRomeV3 => {
+            (0x17, 0x30)
+        }
+        _ => (0x17, 0x00),
+    };
+
+    CpuSignature {
+        family,
+        model,
+        stepping: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_eax_from_eax_round_trips() {
+        // Family >= 0xF: both ExtFamily and ExtModel folded in (e.g. Milan/Genoa).
+        let milan = CpuSignature {
+            family: 0x19,
+            model: 0x11,
+            stepping: 2,
+        };
+        assert_eq!(CpuSignature::from_eax(milan.to_eax()), milan);
+
+        // Family 0x6: ExtModel folded in, ExtFamily is not.
+        let family_6 = CpuSignature {
+            family: 0x6,
+            model: 0xA5,
+            stepping: 3,
+        };
+        assert_eq!(CpuSignature::from_eax(family_6.to_eax()), family_6);
+
+        // Neither extended field in play.
+        let plain = CpuSignature {
+            family: 0x5,
+            model: 0x3,
+            stepping: 7,
+        };
+        assert_eq!(CpuSignature::from_eax(plain.to_eax()), plain);
+    }
+
+    #[test]
+    fn representative_signature_maps_back_to_same_cpu_type() {
+        for cpu_type in [
+            CpuType::EpycMilan,
+            CpuType::EpycGenoa,
+            CpuType::EpycRome,
+            CpuType::Epyc,
+        ] {
+            let sig = representative_signature(cpu_type);
+            assert_eq!(cpu_type_from_signature(sig), Some(cpu_type));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+// Detection of the SEV_FEATURES bitmask that will end up in a guest's VMSA.
+// See Table B-4, "VMSA SEV_FEATURES provided by KVM", in the AMD64
+// Architecture Programmer's Manual, Volume 2 (24593). The bits below are not
+// independent toggles a guest author picks freely -- they reflect whatever
+// the host kernel/KVM actually programs, so `detect_guest_features` is a
+// best-effort inference from the kernel version and the kvm_amd module's
+// `/sys` parameters rather than an authoritative readout.
+
+use std::fs;
+
+pub const SNP_ACTIVE: u64 = 1 << 0;
+pub const V_TOM: u64 = 1 << 1;
+// Not wired into detect_guest_features: there's no `/sys`/CPUID signal that
+// distinguishes "ReflectVC on" or "RestrictedInjection (vs. no injection bit
+// at all)" from the common case where neither is set, so guessing them in
+// would risk a wrong default. Named here so --guest-features hex values can
+// still be decoded against Table B-4 by eye.
+#[allow(dead_code)]
+pub const REFLECT_VC: u64 = 1 << 2;
+#[allow(dead_code)]
+pub const RESTRICTED_INJECTION: u64 = 1 << 3;
+pub const ALTERNATE_INJECTION: u64 = 1 << 4;
+pub const DEBUG_SWAP: u64 = 1 << 5;
+pub const SECURE_TSC: u64 = 1 << 9;
+
+/// Infers the `SEV_FEATURES` mask KVM will program into the guest's VMSA on
+/// this host. DEBUG_SWAP in particular shifts the VMSA layout (and therefore
+/// the launch measurement), so getting its default right matters: recent
+/// kernels enable it unconditionally for SNP guests, which is where the
+/// common `0x21` (SNPActive | DebugSwap) mask comes from. Every other bit
+/// stays off unless a concrete `/sys`/CPUID signal says otherwise -- a
+/// standard KVM/QEMU SNP guest sets neither ReflectVC nor an injection bit,
+/// so guessing them in by default would make `--detect-guest-features`
+/// produce a measurement that doesn't match the real report.
+pub fn detect_guest_features() -> u64 {
+    let mut features = SNP_ACTIVE;
+
+    if debug_swap_default() {
+        features |= DEBUG_SWAP;
+    }
+
+    if avic_enabled() {
+        features |= ALTERNATE_INJECTION;
+    }
+
+    if vtom_supported() {
+        features |= V_TOM;
+    }
+
+    if secure_tsc_supported() {
+        features |= SECURE_TSC;
+    }
+
+    features
+}
+
+/// Host kernel major.minor version, read from `/proc/sys/kernel/osrelease`.
+fn kernel_version() -> Option<(u32, u32)> {
+    let osrelease = fs::read_to_string("/proc/sys/kernel/osrelease").ok()?;
+    let mut parts = osrelease.trim().split(|c: char| c == '.' || c == '-');
+    let major: u32 = parts.next()?.parse().ok()?;
+    let minor: u32 = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// KVM has unconditionally set DEBUG_SWAP for SNP guests since Linux 6.x;
+/// treat that as the default unless overridden on the CLI.
+fn debug_swap_default() -> bool {
+    kernel_version().map(|(major, _)| major >= 6).unwrap_or(false)
+}
+
+/// KVM picks ALTERNATE_INJECTION when AVIC (the APIC virtualization used for
+/// interrupt injection) is enabled. Read via the kvm_amd module's `/sys` CC
+/// parameter rather than guessing; RESTRICTED_INJECTION is left for the
+/// caller to set explicitly via `--guest-features`, since there's no `/sys`
+/// signal that distinguishes "RestrictedInjection" from "no injection bit
+/// set at all".
+fn avic_enabled() -> bool {
+    sys_module_param_is_enabled("/sys/module/kvm_amd/parameters/avic")
+}
+
+/// vTOM (the translate-on-mmio overlay some non-KVM VMMs use) has no kvm_amd
+/// counterpart: upstream KVM always uses the GHCB-based SNP page state model
+/// instead. This probes a `vtom` kvm_amd module parameter, which upstream
+/// kernels do not expose, so on those hosts the read fails and the flag is
+/// not set; it is set only where such a parameter exists and reads enabled.
+fn vtom_supported() -> bool {
+    sys_module_param_is_enabled("/sys/module/kvm_amd/parameters/vtom")
+}
+
+/// CPUID Fn8000_001F_EAX, bit 9: Secure TSC supported.
+fn secure_tsc_supported() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // Safety: CPUID leaf 0x8000_001F (SEV feature info) is read-only and
+        // takes no arguments beyond the leaf number.
+        let result = unsafe { std::arch::x86_64::__cpuid(0x8000_001f) };
+        result.eax & (1 << 9) != 0
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+/// Reads a kvm_amd `/sys/module/kvm_amd/parameters/*` boolean parameter,
+/// which the kernel renders as `Y`/`N` or `1`/`0`.
+fn sys_module_param_is_enabled(path: &str) -> bool {
+    fs::read_to_string(path)
+        .map(|v| matches!(v.trim(), "Y" | "1"))
+        .unwrap_or(false)
+}
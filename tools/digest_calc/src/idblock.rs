@@ -0,0 +1,275 @@
+// Construction and signing of the SNP ID_BLOCK / ID_AUTH_INFORMATION
+// structures that QEMU accepts via `-object sev-snp-guest,id-block=...,id-auth=...`.
+// Layout follows the SEV-SNP ABI: the ID_BLOCK is a little-endian packed
+// struct binding the launch digest to a family/image/version/policy, and the
+// ID_AUTH_INFORMATION carries the ECDSA P-384/SHA-384 signature(s) over it.
+
+use openssl::bn::BigNum;
+use openssl::ec::EcKey;
+use openssl::ecdsa::EcdsaSig;
+use openssl::pkey::{PKey, Private};
+use openssl::sha::sha384;
+use std::fs;
+use std::path::Path;
+
+/// 1 == ECDSA P-384 with SHA-384, the only algorithm QEMU currently accepts.
+pub const ID_KEY_ALGO_ECDSA_P384_SHA384: u32 = 1;
+/// AMD's curve identifier for P-384 inside the packed ECDSA public key struct.
+const EC_CURVE_P384: u32 = 2;
+
+/// Size in bytes of a packed `ecdsa_sig` struct (r, s, reserved).
+pub const ID_BLOCK_SIG_BYTES: usize = 512;
+/// Size in bytes of a packed `ecdsa_pub_key` struct (curve, Qx, Qy, reserved).
+pub const ID_KEY_BYTES: usize = 1028;
+
+/// The SNP ID_BLOCK, serialized little-endian exactly as QEMU/KVM expect it.
+#[derive(Clone, Debug)]
+pub struct IdBlock {
+    pub ld: [u8; 48],
+    pub family_id: [u8; 16],
+    pub image_id: [u8; 16],
+    pub version: u32,
+    pub guest_svn: u32,
+    pub policy: u64,
+}
+
+impl IdBlock {
+    /// Serializes the block to its little-endian wire representation.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(96);
+        buf.extend_from_slice(&self.ld);
+        buf.extend_from_slice(&self.family_id);
+        buf.extend_from_slice(&self.image_id);
+        buf.extend_from_slice(&self.version.to_le_bytes());
+        buf.extend_from_slice(&self.guest_svn.to_le_bytes());
+        buf.extend_from_slice(&self.policy.to_le_bytes());
+        buf
+    }
+}
+
+/// Fixed size of the packed ID_AUTH_INFORMATION struct, per the SEV-SNP
+/// Firmware ABI. Offsets below (0x040, 0x240, 0x680, 0x880) are absolute
+/// positions within this buffer, not relative sizes, and the gaps between
+/// fields are ABI-mandated reserved padding that must stay zeroed:
+///
+///   0x000  ID_KEY_ALGO      (4)
+///   0x004  AUTHOR_KEY_ALGO  (4)
+///   0x008  RESERVED         (56)
+///   0x040  ID_BLOCK_SIG     (512)
+///   0x240  ID_KEY           (1028)
+///   0x644  RESERVED         (60)
+///   0x680  AUTHOR_KEY_SIG   (512)
+///   0x880  AUTHOR_KEY       (1028)
+///   0xc84  RESERVED         (892, to pad out to 4096)
+///
+/// Note AUTHOR_KEY_EN is not a field of this struct: it is a separate flag
+/// passed alongside the id-block/id-auth blobs (the `author-key-en` property
+/// on QEMU's `sev-snp-guest` object, or `auth_key_en` in KVM's
+/// `kvm_sev_snp_launch_finish`), so callers must carry it independently.
+pub const ID_AUTH_INFO_BYTES: usize = 4096;
+
+const ID_BLOCK_SIG_OFFSET: usize = 0x040;
+const ID_KEY_OFFSET: usize = 0x240;
+const AUTHOR_KEY_SIG_OFFSET: usize = 0x680;
+const AUTHOR_KEY_OFFSET: usize = 0x880;
+
+/// The SNP ID_AUTH_INFORMATION structure that accompanies an ID_BLOCK.
+#[derive(Clone, Debug)]
+pub struct IdAuthInfo {
+    pub id_key_algo: u32,
+    pub author_key_algo: u32,
+    pub id_block_sig: [u8; ID_BLOCK_SIG_BYTES],
+    pub id_key: [u8; ID_KEY_BYTES],
+    pub author_sig: [u8; ID_BLOCK_SIG_BYTES],
+    pub author_key: [u8; ID_KEY_BYTES],
+}
+
+impl IdAuthInfo {
+    /// Serializes the struct to its fixed 4096-byte little-endian wire
+    /// representation, leaving the ABI-reserved gaps zeroed.
+    pub fn to_bytes(&self) -> [u8; ID_AUTH_INFO_BYTES] {
+        let mut buf = [0u8; ID_AUTH_INFO_BYTES];
+        buf[0x000..0x004].copy_from_slice(&self.id_key_algo.to_le_bytes());
+        buf[0x004..0x008].copy_from_slice(&self.author_key_algo.to_le_bytes());
+        buf[ID_BLOCK_SIG_OFFSET..ID_BLOCK_SIG_OFFSET + ID_BLOCK_SIG_BYTES]
+            .copy_from_slice(&self.id_block_sig);
+        buf[ID_KEY_OFFSET..ID_KEY_OFFSET + ID_KEY_BYTES].copy_from_slice(&self.id_key);
+        buf[AUTHOR_KEY_SIG_OFFSET..AUTHOR_KEY_SIG_OFFSET + ID_BLOCK_SIG_BYTES]
+            .copy_from_slice(&self.author_sig);
+        buf[AUTHOR_KEY_OFFSET..AUTHOR_KEY_OFFSET + ID_KEY_BYTES].copy_from_slice(&self.author_key);
+        buf
+    }
+}
+
+/// The finished, ready-to-base64 ID_BLOCK / ID_AUTH_INFORMATION pair, plus
+/// the author-key-enable flag QEMU/KVM take as a separate parameter.
+pub struct IdBlockArtifacts {
+    pub id_block: IdBlock,
+    pub id_auth_info: IdAuthInfo,
+    pub author_key_en: bool,
+}
+
+fn load_ec_private_key(path: &Path) -> Result<EcKey<Private>, String> {
+    let pem = fs::read(path).map_err(|e| format!("failed to read key {}: {e}", path.display()))?;
+    let key = PKey::private_key_from_pem(&pem)
+        .map_err(|e| format!("failed to parse EC private key {}: {e}", path.display()))?;
+    key.ec_key()
+        .map_err(|e| format!("key {} is not an EC key: {e}", path.display()))
+}
+
+/// Writes a big-endian `BigNum` into a little-endian field: least-significant
+/// byte at index 0, zero-padded at the high end.
+fn bignum_to_le_field<const N: usize>(n: &BigNum) -> [u8; N] {
+    let be = n.to_vec();
+    let mut out = [0u8; N];
+    for (i, byte) in be.iter().rev().enumerate() {
+        out[i] = *byte;
+    }
+    out
+}
+
+/// Signs `data` with SHA-384 under the given EC-P384 key, returning the
+/// packed 512-byte `ecdsa_sig` struct.
+fn sign_sha384(key: &EcKey<Private>, data: &[u8]) -> Result<[u8; ID_BLOCK_SIG_BYTES], String> {
+    let digest = sha384(data);
+    let sig = EcdsaSig::sign(&digest, key).map_err(|e| format!("ECDSA signing failed: {e}"))?;
+
+    let mut buf = [0u8; ID_BLOCK_SIG_BYTES];
+    buf[0..72].copy_from_slice(&bignum_to_le_field::<72>(sig.r()));
+    buf[72..144].copy_from_slice(&bignum_to_le_field::<72>(sig.s()));
+    Ok(buf)
+}
+
+/// Packs an EC-P384 public key into the 1028-byte `ecdsa_pub_key` struct.
+fn pack_pubkey(key: &EcKey<Private>) -> Result<[u8; ID_KEY_BYTES], String> {
+    let group = key.group();
+    let mut ctx = openssl::bn::BigNumContext::new().map_err(|e| e.to_string())?;
+    let mut x = BigNum::new().map_err(|e| e.to_string())?;
+    let mut y = BigNum::new().map_err(|e| e.to_string())?;
+    key.public_key()
+        .affine_coordinates_gfp(group, &mut x, &mut y, &mut ctx)
+        .map_err(|e| format!("failed to extract public key coordinates: {e}"))?;
+
+    let mut buf = [0u8; ID_KEY_BYTES];
+    buf[0..4].copy_from_slice(&EC_CURVE_P384.to_le_bytes());
+    buf[4..76].copy_from_slice(&bignum_to_le_field::<72>(&x));
+    buf[76..148].copy_from_slice(&bignum_to_le_field::<72>(&y));
+    Ok(buf)
+}
+
+/// Builds the ID_BLOCK from the launch digest and `VMDescription` fields,
+/// then signs it with `id_key_path` and, if given, signs the ID key itself
+/// with `author_key_path`.
+pub fn build_and_sign(
+    ld: [u8; 48],
+    family_id: [u8; 16],
+    image_id: [u8; 16],
+    guest_svn: u32,
+    policy: u64,
+    id_key_path: &Path,
+    author_key_path: Option<&Path>,
+) -> Result<IdBlockArtifacts, String> {
+    let id_block = IdBlock {
+        ld,
+        family_id,
+        image_id,
+        version: 1,
+        guest_svn,
+        policy,
+    };
+    let id_block_bytes = id_block.to_bytes();
+
+    let id_key = load_ec_private_key(id_key_path)?;
+    let id_block_sig = sign_sha384(&id_key, &id_block_bytes)?;
+    let id_key_bytes = pack_pubkey(&id_key)?;
+
+    let (author_key_en, author_sig, author_key) = match author_key_path {
+        Some(path) => {
+            let author_key = load_ec_private_key(path)?;
+            let sig = sign_sha384(&author_key, &id_key_bytes)?;
+            let pubkey = pack_pubkey(&author_key)?;
+            (true, sig, pubkey)
+        }
+        None => (false, [0u8; ID_BLOCK_SIG_BYTES], [0u8; ID_KEY_BYTES]),
+    };
+
+    let id_auth_info = IdAuthInfo {
+        id_key_algo: ID_KEY_ALGO_ECDSA_P384_SHA384,
+        author_key_algo: ID_KEY_ALGO_ECDSA_P384_SHA384,
+        id_block_sig,
+        id_key: id_key_bytes,
+        author_sig,
+        author_key,
+    };
+
+    Ok(IdBlockArtifacts {
+        id_block,
+        id_auth_info,
+        author_key_en,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_block_to_bytes_has_expected_size_and_order() {
+        let block = IdBlock {
+            ld: [0x11; 48],
+            family_id: [0x22; 16],
+            image_id: [0x33; 16],
+            version: 1,
+            guest_svn: 7,
+            policy: 0x1234_5678_9abc_def0,
+        };
+        let bytes = block.to_bytes();
+
+        assert_eq!(bytes.len(), 96);
+        assert_eq!(&bytes[0..48], &[0x11; 48][..]);
+        assert_eq!(&bytes[48..64], &[0x22; 16][..]);
+        assert_eq!(&bytes[64..80], &[0x33; 16][..]);
+        assert_eq!(&bytes[80..84], &1u32.to_le_bytes());
+        assert_eq!(&bytes[84..88], &7u32.to_le_bytes());
+        assert_eq!(&bytes[88..96], &block.policy.to_le_bytes());
+    }
+
+    #[test]
+    fn id_auth_info_to_bytes_places_fields_at_abi_offsets_and_zeroes_gaps() {
+        let info = IdAuthInfo {
+            id_key_algo: ID_KEY_ALGO_ECDSA_P384_SHA384,
+            author_key_algo: ID_KEY_ALGO_ECDSA_P384_SHA384,
+            id_block_sig: [0xAA; ID_BLOCK_SIG_BYTES],
+            id_key: [0xBB; ID_KEY_BYTES],
+            author_sig: [0xCC; ID_BLOCK_SIG_BYTES],
+            author_key: [0xDD; ID_KEY_BYTES],
+        };
+        let bytes = info.to_bytes();
+
+        assert_eq!(bytes.len(), ID_AUTH_INFO_BYTES);
+        assert_eq!(&bytes[0x000..0x004], &ID_KEY_ALGO_ECDSA_P384_SHA384.to_le_bytes());
+        assert_eq!(&bytes[0x004..0x008], &ID_KEY_ALGO_ECDSA_P384_SHA384.to_le_bytes());
+        assert!(bytes[0x008..0x040].iter().all(|&b| b == 0));
+        assert_eq!(&bytes[0x040..0x040 + ID_BLOCK_SIG_BYTES], &[0xAA; ID_BLOCK_SIG_BYTES][..]);
+        assert_eq!(&bytes[0x240..0x240 + ID_KEY_BYTES], &[0xBB; ID_KEY_BYTES][..]);
+        assert!(bytes[0x644..0x680].iter().all(|&b| b == 0));
+        assert_eq!(&bytes[0x680..0x680 + ID_BLOCK_SIG_BYTES], &[0xCC; ID_BLOCK_SIG_BYTES][..]);
+        assert_eq!(&bytes[0x880..0x880 + ID_KEY_BYTES], &[0xDD; ID_KEY_BYTES][..]);
+        assert!(bytes[0xc84..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn bignum_to_le_field_round_trips_through_big_endian_bytes() {
+        let mut n = BigNum::new().unwrap();
+        n.rand(100, openssl::bn::MsbOption::MAYBE_ZERO, false).unwrap();
+
+        let field = bignum_to_le_field::<72>(&n);
+
+        // Re-derive a BigNum from the little-endian field by reversing it
+        // back to big-endian, and confirm it matches the original value.
+        let mut be = field;
+        be.reverse();
+        let round_tripped = BigNum::from_slice(&be).unwrap();
+        assert_eq!(round_tripped.to_vec(), n.to_vec());
+    }
+}
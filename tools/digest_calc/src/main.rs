@@ -4,12 +4,15 @@
 // and generates the corresponding launch digest required for secure attestation 
 // in SEV-SNP environments.
 
+use base64::{engine::general_purpose::STANDARD as base64_engine, Engine as _};
 use bincode;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 use sev::error::MeasurementError;
 use sev::firmware::guest::{GuestPolicy, PlatformInfo};
 use sev::firmware::host::TcbVersion;
+use sev::measurement::sev::{sev_calc_launch_digest, SevMeasurementArgs};
+use sev::measurement::sev_es::{seves_calc_launch_digest, SevEsMeasurementArgs};
 use sev::measurement::sev_hashes::SevHashes;
 use sev::measurement::snp::{
     calc_snp_ovmf_hash, snp_calc_launch_digest, SnpLaunchDigest, SnpMeasurementArgs,
@@ -21,9 +24,15 @@ use std::fmt::Display;
 use std::fs;
 
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use hex_buffer_serde::{Hex as _, HexForm};
 
+mod cpuid;
+mod features;
+mod idblock;
+mod verify;
+mod vmsa_dump;
+
 ///Length fo the FamilyID and the ImageID data types in bytes
 pub const IDBLOCK_ID_BYTES :usize = 16;
 
@@ -47,10 +56,23 @@ impl Display for ProductName {
 
 
 /// Converts a byte slice to a hexadecimal string representation.
-fn bytes_to_hex(bytes: &[u8]) -> String {
+pub(crate) fn bytes_to_hex(bytes: &[u8]) -> String {
     bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
 }
 
+/// Converts a byte slice to a base64 string representation.
+fn bytes_to_base64(bytes: &[u8]) -> String {
+    base64_engine.encode(bytes)
+}
+
+/// Renders bytes using the requested `OutputFormat`.
+fn encode_bytes(format: OutputFormat, bytes: &[u8]) -> String {
+    match format {
+        OutputFormat::Hex => bytes_to_hex(bytes),
+        OutputFormat::Base64 => bytes_to_base64(bytes),
+    }
+}
+
 /// Calculates the launch measurement digest using the SEV-SNP arguments.
 fn calculate_launch_measurment(
     snp_measure_args: SnpMeasurementArgs,
@@ -75,11 +97,42 @@ fn calculate_launch_measurment(
     Ok(ld_arr)
 }
 
+/// Calculates the launch measurement digest for a plain SEV guest.
+fn calculate_sev_launch_measurement(sev_measure_args: SevMeasurementArgs) -> Result<Vec<u8>, String> {
+    let digest = sev_calc_launch_digest(sev_measure_args)
+        .map_err(|e| format!("Failed to compute SEV launch digest: {:?}", e))?;
+
+    bincode::serialize(&digest)
+        .map_err(|e| format!("Failed to bincode serialize SEV launch digest: {:?}", e))
+}
+
+/// Calculates the launch measurement digest for a SEV-ES guest.
+fn calculate_seves_launch_measurement(
+    seves_measure_args: SevEsMeasurementArgs,
+) -> Result<Vec<u8>, String> {
+    let digest = seves_calc_launch_digest(seves_measure_args)
+        .map_err(|e| format!("Failed to compute SEV-ES launch digest: {:?}", e))?;
+
+    bincode::serialize(&digest)
+        .map_err(|e| format!("Failed to bincode serialize SEV-ES launch digest: {:?}", e))
+}
+
 /// Calculates the OVMF file hash.
 pub fn get_ovmf_hash_from_file(ovmf_file: PathBuf) -> Result<SnpLaunchDigest, MeasurementError> {
     calc_snp_ovmf_hash(ovmf_file)
 }
 
+/// Calculates the plain SEV/SEV-ES `ovmf_hash_str`: a single SHA-256 over
+/// the whole firmware image. This is distinct from `get_ovmf_hash_from_file`
+/// above, which computes SNP's page-by-page launch digest contribution --
+/// plain SEV/SEV-ES never had a page-granular measurement model, so their
+/// `ovmf_hash_str` is the traditional whole-file hash instead.
+pub fn get_ovmf_sha256_hex(ovmf_file: &Path) -> Result<String, String> {
+    let bytes = fs::read(ovmf_file)
+        .map_err(|e| format!("failed to read OVMF file {}: {e}", ovmf_file.display()))?;
+    Ok(bytes_to_hex(&openssl::sha::sha256(&bytes)))
+}
+
 /// Retrieves the hashes for kernel, initrd, and cmdline files.
 pub fn get_hashes_from_files(
     kernel_file: PathBuf,
@@ -89,15 +142,249 @@ pub fn get_hashes_from_files(
     SevHashes::new(kernel_file, Some(initrd_file), append)
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum, Default)]
+///Which launch-digest routine to run
+pub enum Mode {
+    ///Plain SEV launch digest
+    Sev,
+    ///SEV-ES launch digest
+    Seves,
+    ///Full SEV-SNP launch digest (kernel, initrd, cmdline, OVMF)
+    #[default]
+    Snp,
+    ///Only the OVMF hash that feeds into the SNP launch digest
+    #[value(name = "snp:ovmf-hash")]
+    SnpOvmfHash,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum, Default)]
+///Encoding used to render hash fields in the output
+pub enum OutputFormat {
+    #[default]
+    Hex,
+    Base64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum, Default)]
+///VMM that will launch the guest, mapped onto the `sev` crate's `VMMType`
+pub enum VmmTypeArg {
+    #[default]
+    Qemu,
+    Ec2,
+    Krun,
+}
+
+impl From<VmmTypeArg> for VMMType {
+    fn from(value: VmmTypeArg) -> Self {
+        match value {
+            VmmTypeArg::Qemu => VMMType::QEMU,
+            VmmTypeArg::Ec2 => VMMType::EC2,
+            VmmTypeArg::Krun => VMMType::KRUN,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+///Explicit `CpuType` override for `--vcpu-type`, bypassing CPUID detection
+pub enum VcpuTypeArg {
+    Epyc,
+    EpycV1,
+    EpycV2,
+    EpycIbpb,
+    EpycV3,
+    EpycV4,
+    EpycRome,
+    EpycRomeV1,
+    EpycRomeV2,
+    EpycRomeV3,
+    EpycMilan,
+    EpycMilanV1,
+    EpycMilanV2,
+    EpycGenoa,
+    EpycGenoaV1,
+}
+
+impl From<VcpuTypeArg> for CpuType {
+    fn from(value: VcpuTypeArg) -> Self {
+        match value {
+            VcpuTypeArg::Epyc => CpuType::Epyc,
+            VcpuTypeArg::EpycV1 => CpuType::EpycV1,
+            VcpuTypeArg::EpycV2 => CpuType::EpycV2,
+            VcpuTypeArg::EpycIbpb => CpuType::EpycIBPB,
+            VcpuTypeArg::EpycV3 => CpuType::EpycV3,
+            VcpuTypeArg::EpycV4 => CpuType::EpycV4,
+            VcpuTypeArg::EpycRome => CpuType::EpycRome,
+            VcpuTypeArg::EpycRomeV1 => CpuType::EpycRomeV1,
+            VcpuTypeArg::EpycRomeV2 => CpuType::EpycRomeV2,
+            VcpuTypeArg::EpycRomeV3 => CpuType::EpycRomeV3,
+            VcpuTypeArg::EpycMilan => CpuType::EpycMilan,
+            VcpuTypeArg::EpycMilanV1 => CpuType::EpycMilanV1,
+            VcpuTypeArg::EpycMilanV2 => CpuType::EpycMilanV2,
+            VcpuTypeArg::EpycGenoa => CpuType::EpycGenoa,
+            VcpuTypeArg::EpycGenoaV1 => CpuType::EpycGenoaV1,
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(
     version,
     about,
 )]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     ///Path to the vm config toml file. This is require to compute the expected attestation value for the VM
     #[arg(long)]
     vm_definition: String,
+
+    ///Which measurement to compute
+    #[arg(long, value_enum, default_value_t = Mode::Snp)]
+    mode: Mode,
+
+    ///Encoding used for the hash fields in the output (hex or base64). QEMU's
+    ///SEV objects (id-block, measurement) consume base64, so select that when
+    ///piping output directly into launch tooling.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Hex)]
+    output_format: OutputFormat,
+
+    ///PEM path to the ECDSA P-384 ID key. When set (snp mode only), the
+    ///computed launch digest is assembled into an ID_BLOCK and signed,
+    ///producing the `id-block`/`id-auth` blobs QEMU's sev-snp-guest object
+    ///accepts.
+    #[arg(long)]
+    id_key: Option<PathBuf>,
+
+    ///PEM path to the ECDSA P-384 author key used to co-sign the ID key.
+    ///Only meaningful together with --id-key.
+    #[arg(long)]
+    author_key: Option<PathBuf>,
+
+    ///GUEST_SVN recorded in the ID_BLOCK. Only meaningful together with --id-key.
+    #[arg(long, default_value_t = 0)]
+    guest_svn: u32,
+
+    ///Overrides `guest_features` from the vm definition with this hex value
+    ///(e.g. 0x21). Takes precedence over --detect-guest-features.
+    #[arg(long, value_parser=parse_hex_u64)]
+    guest_features: Option<u64>,
+
+    ///Infers `guest_features` from this host's kernel/CC attributes instead
+    ///of using the value in the vm definition.
+    #[arg(long)]
+    detect_guest_features: bool,
+
+    ///Explicit vCPU type override (e.g. epyc-milan-v2), bypassing CPUID detection.
+    #[arg(long, value_enum)]
+    vcpu_type: Option<VcpuTypeArg>,
+
+    ///Explicit raw CPUID.01H:EAX vCPU signature override (hex), bypassing
+    ///detection. Decoded into family/model/stepping the same way CPUID is.
+    #[arg(long, value_parser=parse_hex_u32)]
+    vcpu_sig: Option<u32>,
+
+    ///Explicit CPU family override, used together with --vcpu-model/--vcpu-stepping.
+    #[arg(long)]
+    vcpu_family: Option<u32>,
+
+    ///Explicit CPU model override, used together with --vcpu-family/--vcpu-stepping.
+    #[arg(long)]
+    vcpu_model: Option<u32>,
+
+    ///Explicit CPU stepping override, used together with --vcpu-family/--vcpu-model.
+    #[arg(long)]
+    vcpu_stepping: Option<u32>,
+
+    ///VMM that will launch the guest (affects certain VMSA fields QEMU/KVM programs).
+    #[arg(long, value_enum, default_value_t = VmmTypeArg::Qemu)]
+    vmm_type: VmmTypeArg,
+
+    ///Writes the per-vCPU VMSA page(s) built for the resolved vcpu_type,
+    ///vmm_type, and guest_features to this path (snp mode only), so a
+    ///measurement mismatch can be diagnosed field-by-field.
+    #[arg(long)]
+    dump_vmsa: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Validate a real SEV-SNP attestation report against the values derived from --vm-definition
+    Verify(VerifyArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyArgs {
+    ///Path to a binary SEV-SNP attestation report (e.g. read from /dev/sev-guest)
+    #[arg(long)]
+    report: PathBuf,
+}
+
+/// Parses a `0x`-prefixed or bare hex string into a `u32`.
+fn parse_hex_u32(s: &str) -> Result<u32, String> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    u32::from_str_radix(s, 16).map_err(|e| format!("invalid hex value '{s}': {e}"))
+}
+
+/// Resolves the vCPU type to measure against: an explicit `--vcpu-type`
+/// name wins, then an explicit signature (`--vcpu-sig` or the
+/// family/model/stepping trio), falling back to CPUID detection on this host.
+fn resolve_vcpu_type(args: &Args) -> CpuType {
+    if let Some(vcpu_type) = args.vcpu_type {
+        return vcpu_type.into();
+    }
+
+    let sig = if let Some(raw) = args.vcpu_sig {
+        Some(cpuid::CpuSignature::from_eax(raw))
+    } else if args.vcpu_family.is_some() || args.vcpu_model.is_some() || args.vcpu_stepping.is_some() {
+        Some(cpuid::CpuSignature {
+            family: args.vcpu_family.unwrap_or(0),
+            model: args.vcpu_model.unwrap_or(0),
+            stepping: args.vcpu_stepping.unwrap_or(0),
+        })
+    } else {
+        cpuid::detect_cpu_signature()
+    };
+
+    sig.and_then(cpuid::cpu_type_from_signature)
+        .unwrap_or(CpuType::EpycV4)
+}
+
+/// Resolves the raw CPUID.01H:EAX vCPU signature SEV-ES's `vcpu_sig` expects.
+/// Unlike SNP's `CpuType` enum, SEV-ES's launch digest folds in the actual
+/// CPUID signature bits, so `vcpu_type as u32` (the enum discriminant) is
+/// the wrong value here even when the resolved `CpuType` is correct.
+/// Precedence: explicit `--vcpu-sig`, then explicit family/model/stepping,
+/// then host CPUID (only when no `--vcpu-type` was given), falling back to a
+/// representative signature for the resolved `CpuType`. Unlike
+/// `resolve_vcpu_type`, an explicit signature here wins over `--vcpu-type`.
+fn resolve_vcpu_sig(args: &Args, vcpu_type: CpuType) -> u32 {
+    if let Some(raw) = args.vcpu_sig {
+        return raw;
+    }
+
+    if args.vcpu_family.is_some() || args.vcpu_model.is_some() || args.vcpu_stepping.is_some() {
+        let sig = cpuid::CpuSignature {
+            family: args.vcpu_family.unwrap_or(0),
+            model: args.vcpu_model.unwrap_or(0),
+            stepping: args.vcpu_stepping.unwrap_or(0),
+        };
+        return sig.to_eax();
+    }
+
+    if args.vcpu_type.is_none() {
+        if let Some(raw) = cpuid::detect_raw_eax() {
+            return raw;
+        }
+    }
+
+    cpuid::representative_signature(vcpu_type).to_eax()
+}
+
+/// Parses a `0x`-prefixed or bare hex string into a `u64`.
+fn parse_hex_u64(s: &str) -> Result<u64, String> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    u64::from_str_radix(s, 16).map_err(|e| format!("invalid hex value '{s}': {e}"))
 }
 
 
@@ -142,48 +429,79 @@ fn main() {
         vmmtype: u32,
         guest_features: String,
         expected_hash: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id_block: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id_auth_info: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        author_key_en: Option<bool>,
     }
 
     let args = Args::parse();
 
     let vm_description: VMDescription = toml::from_str(&fs::read_to_string(&args.vm_definition).unwrap()).unwrap();
 
+    if args.mode == Mode::SnpOvmfHash {
+        let omvf_file: PathBuf = vm_description.ovmf_file.clone().into();
+        let ovmf_hash = get_ovmf_hash_from_file(omvf_file).unwrap();
+        let ovmf_bytes: Vec<u8> = bincode::serialize(&ovmf_hash).unwrap();
+
+        #[derive(Debug, Serialize)]
+        struct OvmfHashOutput {
+            ovmf_hash: String,
+        }
+
+        let output = OvmfHashOutput {
+            ovmf_hash: encode_bytes(args.output_format, &ovmf_bytes),
+        };
+
+        println!("{}", serde_json::to_string_pretty(&output).unwrap());
+        return;
+    }
+
     let vcpus: u32 = vm_description.vcpu_count;
 
-    let vcpu_type_str = "EpycV4";
-    let vcpu_type = match vcpu_type_str {
-        "Epyc" => CpuType::Epyc,
-        "EpycV1" => CpuType::EpycV1,
-        "EpycV2" => CpuType::EpycV2,
-        "EpycIBPB" => CpuType::EpycIBPB,
-        "EpycV3" => CpuType::EpycV3,
-        "EpycV4" => CpuType::EpycV4,
-        "EpycRome" => CpuType::EpycRome,
-        "EpycRomeV1" => CpuType::EpycRomeV1,
-        "EpycRomeV2" => CpuType::EpycRomeV2,
-        "EpycRomeV3" => CpuType::EpycRomeV3,
-        "EpycMilan" => CpuType::EpycMilan,
-        "EpycMilanV1" => CpuType::EpycMilanV1,
-        "EpycMilanV2" => CpuType::EpycMilanV2,
-        "EpycGenoa" => CpuType::EpycGenoa,
-        "EpycGenoaV1" => CpuType::EpycGenoaV1,
-        _ => CpuType::EpycV4, // Default to EpycV4
-    };
+    let vcpu_type = resolve_vcpu_type(&args);
 
-    let vmm_type_str = "QEMU";
-    let vmm_type = match vmm_type_str {
-        "QEMU" => Some(VMMType::QEMU),
-        "EC2" => Some(VMMType::EC2),
-        "KRUN" => Some(VMMType::KRUN),
-        _ => Some(VMMType::QEMU),
-    };
+    let vmm_type: Option<VMMType> = Some(args.vmm_type.into());
 
 
-    let guest_features_string  = format!("0x{:X}", vm_description.guest_features);
-    let guest_features: u64 =
-    u64::from_str_radix(&guest_features_string[2..], 16).unwrap();
+    let guest_features: u64 = if let Some(overridden) = args.guest_features {
+        overridden
+    } else if args.detect_guest_features {
+        features::detect_guest_features()
+    } else {
+        vm_description.guest_features
+    };
+    let guest_features_string = format!("0x{:X}", guest_features);
 
     let omvf_file: PathBuf = vm_description.ovmf_file.clone().into();
+
+    if let Some(dump_path) = &args.dump_vmsa {
+        if args.mode != Mode::Snp {
+            panic!("--dump-vmsa is only supported in snp mode");
+        }
+
+        let pages = vmsa_dump::dump_vmsas(
+            dump_path,
+            &omvf_file,
+            vcpus,
+            vcpu_type,
+            vmm_type.unwrap(),
+            GuestFeatures(guest_features),
+        )
+        .unwrap();
+
+        eprintln!(
+            "wrote {} VMSA page(s) to {}",
+            pages.len(),
+            dump_path.display()
+        );
+        for (i, hex) in pages.iter().enumerate() {
+            eprintln!("vcpu[{i}]: {hex}");
+        }
+    }
+
     // Step 1: Get the hash of the OVMF file
     let ovmf_hash = get_ovmf_hash_from_file(omvf_file.clone()).unwrap();
     let ovmf_bytes: Vec<u8> = bincode::serialize(&ovmf_hash).unwrap();
@@ -202,36 +520,109 @@ fn main() {
     .unwrap();
 
     // Step 3: Calculate the launch digest
-    let arguments = SnpMeasurementArgs {
-        ovmf_file: Some(omvf_file),
-        kernel_file: None,
-        initrd_file: None,
-        append: None,
-
-        vcpus,
-        vcpu_type,
-        vmm_type,
-        guest_features: GuestFeatures(guest_features),
-
-        ovmf_hash_str: Some(ovmf_binding.as_str()),
-        kernel_hash: Some(kernel_hash),
-        initrd_hash: Some(initrd_hash),
-        append_hash: Some(cmdline_hash),
+    let expected_hash: Vec<u8> = match args.mode {
+        Mode::Snp => {
+            let arguments = SnpMeasurementArgs {
+                ovmf_file: Some(omvf_file),
+                kernel_file: None,
+                initrd_file: None,
+                append: None,
+
+                vcpus,
+                vcpu_type,
+                vmm_type,
+                guest_features: GuestFeatures(guest_features),
+
+                ovmf_hash_str: Some(ovmf_binding.as_str()),
+                kernel_hash: Some(kernel_hash),
+                initrd_hash: Some(initrd_hash),
+                append_hash: Some(cmdline_hash),
+            };
+
+            calculate_launch_measurment(arguments).unwrap().to_vec()
+        }
+        Mode::Sev => {
+            let ovmf_sha256 = get_ovmf_sha256_hex(&omvf_file).unwrap();
+            let arguments = SevMeasurementArgs {
+                ovmf_hash_str: ovmf_sha256.as_str(),
+                kernel_hash: Some(kernel_hash),
+                initrd_hash: Some(initrd_hash),
+                append_hash: Some(cmdline_hash),
+            };
+
+            calculate_sev_launch_measurement(arguments).unwrap()
+        }
+        Mode::Seves => {
+            let ovmf_sha256 = get_ovmf_sha256_hex(&omvf_file).unwrap();
+            let arguments = SevEsMeasurementArgs {
+                ovmf_hash_str: ovmf_sha256.as_str(),
+                kernel_hash: Some(kernel_hash),
+                initrd_hash: Some(initrd_hash),
+                append_hash: Some(cmdline_hash),
+                vcpus,
+                vcpu_sig: resolve_vcpu_sig(&args, vcpu_type),
+            };
+
+            calculate_seves_launch_measurement(arguments).unwrap()
+        }
+        Mode::SnpOvmfHash => unreachable!("handled above"),
     };
 
-    let expected_hash = calculate_launch_measurment(arguments).unwrap();
+    if let Some(Command::Verify(verify_args)) = &args.command {
+        if args.mode != Mode::Snp {
+            panic!("verify is only supported in snp mode");
+        }
+
+        let result = verify::verify_report(&verify_args.report, &expected_hash, &vm_description)
+            .unwrap();
 
+        println!("{}", serde_json::to_string_pretty(&result).unwrap());
+        std::process::exit(if result.pass { 0 } else { 1 });
+    }
+
+    // Step 4: Optionally bind the digest to the configured signing keys by
+    // assembling and signing the ID_BLOCK / ID_AUTH_INFORMATION.
+    let (id_block, id_auth_info, author_key_en) = match (&args.id_key, args.mode) {
+        (Some(id_key_path), Mode::Snp) => {
+            let ld: [u8; 48] = expected_hash
+                .clone()
+                .try_into()
+                .expect("SNP launch digest has unexpected length");
+
+            let artifacts = idblock::build_and_sign(
+                ld,
+                vm_description.family_id,
+                vm_description.image_id,
+                args.guest_svn,
+                vm_description.guest_policy.into(),
+                id_key_path,
+                args.author_key.as_deref(),
+            )
+            .unwrap();
+
+            (
+                Some(base64_engine.encode(artifacts.id_block.to_bytes())),
+                Some(base64_engine.encode(artifacts.id_auth_info.to_bytes())),
+                Some(artifacts.author_key_en),
+            )
+        }
+        (Some(_), _) => panic!("--id-key is only supported in snp mode"),
+        (None, _) => (None, None, None),
+    };
 
     let output = Output {
-        kernel_hash: bytes_to_hex(&kernel_hash),
-        initrd_hash: bytes_to_hex(&initrd_hash),
-        cmdline_hash: bytes_to_hex(&cmdline_hash),
-        ovmf_hash: bytes_to_hex(&ovmf_bytes),
+        kernel_hash: encode_bytes(args.output_format, &kernel_hash),
+        initrd_hash: encode_bytes(args.output_format, &initrd_hash),
+        cmdline_hash: encode_bytes(args.output_format, &cmdline_hash),
+        ovmf_hash: encode_bytes(args.output_format, &ovmf_bytes),
         vcpus,
         vcputype: vcpu_type as u32,
         vmmtype: vmm_type.unwrap() as u32,
         guest_features: guest_features_string,
-        expected_hash:  bytes_to_hex(&expected_hash)
+        expected_hash: encode_bytes(args.output_format, &expected_hash),
+        id_block,
+        id_auth_info,
+        author_key_en,
     };
 
     println!("{}", serde_json::to_string_pretty(&output).unwrap());
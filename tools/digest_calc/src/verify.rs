@@ -0,0 +1,93 @@
+// Validates a real SEV-SNP attestation report (as read from /dev/sev-guest
+// or dumped to a file) against the values derived from a `VMDescription`,
+// turning the digest calculator into a full launch-integrity checker rather
+// than a value printer that has to be diffed by hand downstream.
+
+use crate::{bytes_to_hex, VMDescription};
+use serde::Serialize;
+use sev::firmware::guest::AttestationReport;
+use sev::firmware::host::TcbVersion;
+use std::path::Path;
+
+/// `TcbVersion`'s derived `Ord` compares fields in declaration order
+/// (bootloader, tee, snp, microcode), so a report with a higher bootloader
+/// but a rolled-back microcode would compare `Greater` and pass a plain
+/// `>=`. An anti-rollback gate must require every component to be at least
+/// the configured minimum individually.
+fn tcb_at_least(actual: &TcbVersion, minimum: &TcbVersion) -> bool {
+    actual.bootloader >= minimum.bootloader
+        && actual.tee >= minimum.tee
+        && actual.snp >= minimum.snp
+        && actual.microcode >= minimum.microcode
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldCheck {
+    pub field: String,
+    pub pass: bool,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyResult {
+    pub pass: bool,
+    pub checks: Vec<FieldCheck>,
+}
+
+/// Reads the attestation report at `report_path` and checks it against the
+/// launch digest just computed and the rest of the `VMDescription`: the
+/// MEASUREMENT must match byte-for-byte, REPORTED_TCB must be at least
+/// `min_commited_tcb`, and POLICY/FAMILY_ID/IMAGE_ID must equal the
+/// configured values.
+pub fn verify_report(
+    report_path: &Path,
+    expected_digest: &[u8],
+    vm_description: &VMDescription,
+) -> Result<VerifyResult, String> {
+    let bytes = std::fs::read(report_path).map_err(|e| {
+        format!(
+            "failed to read attestation report {}: {e}",
+            report_path.display()
+        )
+    })?;
+
+    let report: AttestationReport = bincode::deserialize(&bytes)
+        .map_err(|e| format!("failed to parse attestation report: {e:?}"))?;
+
+    let checks = vec![
+        FieldCheck {
+            field: "measurement".to_string(),
+            pass: report.measurement[..] == expected_digest[..],
+            expected: bytes_to_hex(expected_digest),
+            actual: bytes_to_hex(&report.measurement),
+        },
+        FieldCheck {
+            field: "reported_tcb".to_string(),
+            pass: tcb_at_least(&report.reported_tcb, &vm_description.min_commited_tcb),
+            expected: format!(">= {:?}", vm_description.min_commited_tcb),
+            actual: format!("{:?}", report.reported_tcb),
+        },
+        FieldCheck {
+            field: "policy".to_string(),
+            pass: report.policy == vm_description.guest_policy,
+            expected: format!("{:?}", vm_description.guest_policy),
+            actual: format!("{:?}", report.policy),
+        },
+        FieldCheck {
+            field: "family_id".to_string(),
+            pass: report.family_id == vm_description.family_id,
+            expected: bytes_to_hex(&vm_description.family_id),
+            actual: bytes_to_hex(&report.family_id),
+        },
+        FieldCheck {
+            field: "image_id".to_string(),
+            pass: report.image_id == vm_description.image_id,
+            expected: bytes_to_hex(&vm_description.image_id),
+            actual: bytes_to_hex(&report.image_id),
+        },
+    ];
+
+    let pass = checks.iter().all(|check| check.pass);
+    Ok(VerifyResult { pass, checks })
+}
@@ -0,0 +1,100 @@
+// Dumps the per-vCPU VMSA page(s) that feed the SNP launch digest, so a
+// measurement mismatch can be diagnosed field-by-field (reset-state register
+// values, feature bits) instead of guessed at.
+
+use crate::bytes_to_hex;
+use sev::measurement::vcpu_types::CpuType;
+use sev::measurement::vmsa::{GuestFeatures, VMMType, VMSA};
+use std::fs;
+use std::path::Path;
+
+/// OVMF "table footer" GUID terminating the GUIDed table QEMU/edk2 append to
+/// the end of the firmware image.
+const FOOTER_GUID: [u8; 16] = [
+    0xde, 0x82, 0xb5, 0x96, 0xb2, 0x1f, 0xf7, 0x45, 0xba, 0xea, 0xa3, 0x66, 0xc5, 0x5a, 0x08, 0x2d,
+];
+/// "SEV-ES Reset Block" table entry: a 4-byte little-endian guest-physical
+/// address APs start executing at, distinct from the BSP's real-mode reset
+/// vector. `snp_calc_launch_digest` reads this same table to build the AP
+/// VMSAs, so a VMSA dump has to follow it too or it won't reflect what was
+/// actually hashed.
+const SEV_ES_RESET_BLOCK_GUID: [u8; 16] = [
+    0xde, 0x71, 0xf7, 0x00, 0x7e, 0x1a, 0xcb, 0x4f, 0x89, 0x0e, 0x68, 0xc7, 0x7e, 0x2f, 0xb4, 0x4e,
+];
+
+/// Finds the AP reset vector address in the OVMF image's table footer, if
+/// the firmware declares one. Returns `None` for firmware without the
+/// SEV-ES/SNP reset block entry (e.g. non-SEV builds), in which case callers
+/// should fall back to the BSP reset vector for every vCPU.
+fn find_ap_reset_eip(ovmf_file: &Path) -> Option<u32> {
+    let data = fs::read(ovmf_file).ok()?;
+    if data.len() < 32 {
+        return None;
+    }
+
+    let footer_guid_start = data.len() - 16;
+    if data[footer_guid_start..] != FOOTER_GUID {
+        return None;
+    }
+
+    let table_len = u16::from_le_bytes([data[data.len() - 18], data[data.len() - 17]]) as usize;
+    let table_start = data.len().checked_sub(table_len)?;
+    let mut cursor = data.len() - 18;
+
+    while cursor > table_start {
+        if cursor < 18 {
+            break;
+        }
+        // Each entry is `<data><UINT16 len><GUID>`, with the GUID in the
+        // last 16 bytes and the 2-byte length immediately before it -- the
+        // same layout as the table footer itself, just repeated per entry.
+        let guid = &data[cursor - 16..cursor];
+        let entry_len = u16::from_le_bytes([data[cursor - 18], data[cursor - 17]]) as usize;
+        if entry_len < 18 || entry_len > cursor {
+            break;
+        }
+        let entry_start = cursor - entry_len;
+        if guid == SEV_ES_RESET_BLOCK_GUID {
+            let payload = &data[entry_start..cursor - 18];
+            if let Ok(addr) = payload.try_into() {
+                return Some(u32::from_le_bytes(addr));
+            }
+        }
+        cursor = entry_start;
+    }
+
+    None
+}
+
+/// Builds the VMSA page for each vCPU implied by `vcpus`, in the same shape
+/// `snp_calc_launch_digest` hashes -- vCPU 0 (the BSP) reset at the standard
+/// real-mode vector, every other vCPU (an AP) reset at the address declared
+/// in `ovmf_file`'s SEV-ES reset block -- writes them concatenated to
+/// `path`, and returns a hex dump of each page for inline inspection.
+pub fn dump_vmsas(
+    path: &Path,
+    ovmf_file: &Path,
+    vcpus: u32,
+    vcpu_type: CpuType,
+    vmm_type: VMMType,
+    guest_features: GuestFeatures,
+) -> Result<Vec<String>, String> {
+    let ap_reset_eip = find_ap_reset_eip(ovmf_file).unwrap_or(0);
+
+    let mut all_bytes = Vec::new();
+    let mut hex_dumps = Vec::new();
+
+    for vcpu in 0..vcpus {
+        let reset_eip = if vcpu == 0 { 0 } else { ap_reset_eip };
+        let vmsa = VMSA::new(reset_eip as u64, vcpu_type, vmm_type, guest_features);
+        let bytes = bincode::serialize(&vmsa)
+            .map_err(|e| format!("failed to serialize VMSA: {e:?}"))?;
+        hex_dumps.push(bytes_to_hex(&bytes));
+        all_bytes.extend_from_slice(&bytes);
+    }
+
+    fs::write(path, &all_bytes)
+        .map_err(|e| format!("failed to write VMSA dump to {}: {e}", path.display()))?;
+
+    Ok(hex_dumps)
+}